@@ -0,0 +1,107 @@
+use ssb_crypto::PublicKey;
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+
+/// Runtime-mutable policy deciding which public keys this node will accept
+/// an inbound handshake from, or dial outbound. A non-empty allowlist
+/// restricts connections to just those keys; the blocklist always takes
+/// priority over the allowlist, so a pinned-then-banned peer stays banned.
+#[derive(Default)]
+pub struct ConnectList {
+    allow: HashSet<PublicKey>,
+    block: HashSet<PublicKey>,
+}
+
+impl ConnectList {
+    pub fn new() -> ConnectList {
+        ConnectList::default()
+    }
+
+    pub fn allow(&mut self, public_key: PublicKey) {
+        self.allow.insert(public_key);
+    }
+
+    pub fn remove_allow(&mut self, public_key: &PublicKey) {
+        self.allow.remove(public_key);
+    }
+
+    pub fn block(&mut self, public_key: PublicKey) {
+        self.block.insert(public_key);
+    }
+
+    pub fn remove_block(&mut self, public_key: &PublicKey) {
+        self.block.remove(public_key);
+    }
+
+    /// Whether a connection to/from `public_key` is permitted.
+    pub fn permits(&self, public_key: &PublicKey) -> bool {
+        if self.block.contains(public_key) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.contains(public_key)
+    }
+}
+
+/// `ConnectList` shared across every `Handshaker` clone handed to a
+/// connection, so the UI can ban or pin a peer while connections are live.
+pub type SharedConnectList = Arc<RwLock<ConnectList>>;
+
+pub fn shared(connect_list: ConnectList) -> SharedConnectList {
+    Arc::new(RwLock::new(connect_list))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pk(byte: u8) -> PublicKey {
+        PublicKey::from_slice(&[byte; 32]).unwrap()
+    }
+
+    #[test]
+    fn empty_allow_permits_everyone() {
+        let list = ConnectList::new();
+        assert!(list.permits(&pk(1)));
+    }
+
+    #[test]
+    fn non_empty_allow_restricts_to_listed_keys() {
+        let mut list = ConnectList::new();
+        list.allow(pk(1));
+        assert!(list.permits(&pk(1)));
+        assert!(!list.permits(&pk(2)));
+    }
+
+    #[test]
+    fn block_overrides_allow() {
+        let mut list = ConnectList::new();
+        list.allow(pk(1));
+        list.block(pk(1));
+        assert!(!list.permits(&pk(1)));
+    }
+
+    #[test]
+    fn block_overrides_an_empty_allow_too() {
+        let mut list = ConnectList::new();
+        list.block(pk(1));
+        assert!(!list.permits(&pk(1)));
+        assert!(list.permits(&pk(2)));
+    }
+
+    #[test]
+    fn removing_a_block_restores_permission() {
+        let mut list = ConnectList::new();
+        list.block(pk(1));
+        list.remove_block(&pk(1));
+        assert!(list.permits(&pk(1)));
+    }
+
+    #[test]
+    fn removing_an_allow_falls_back_to_allow_all() {
+        let mut list = ConnectList::new();
+        list.allow(pk(1));
+        list.remove_allow(&pk(1));
+        assert!(list.permits(&pk(1)));
+        assert!(list.permits(&pk(2)));
+    }
+}
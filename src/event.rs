@@ -2,7 +2,8 @@ use crate::discovery::{DiscoveryService, Mode, PeerAddr};
 use crate::peer_manager::PeerManagerEvent;
 use ssb_crypto::PublicKey;
 use std::io;
-use std::sync::mpsc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
 use std::thread;
 use std::time::Duration;
 use termion::event::Key;
@@ -17,12 +18,32 @@ pub enum Event<I> {
 
 pub struct Events {
     rx: mpsc::Receiver<Event<Key>>,
+    tx: mpsc::Sender<Event<Key>>,
+    shutdown: Arc<AtomicBool>,
     _input_handle: thread::JoinHandle<()>,
     _tick_handle: thread::JoinHandle<()>,
     _new_peer_handle: thread::JoinHandle<()>,
     _pm_handle: thread::JoinHandle<()>,
 }
 
+/// Lets code outside `Events` (e.g. gossip-driven peer discovery in
+/// `peer_connection`) feed a `PeerAddr` into the exact same queue that
+/// local UDP discovery uses, so the rest of the app doesn't need to know
+/// where a `NewPeer` came from.
+#[derive(Clone)]
+pub struct NewPeerSender(mpsc::Sender<Event<Key>>);
+
+impl NewPeerSender {
+    pub fn send(&self, peer: PeerAddr) -> Result<(), mpsc::SendError<PeerAddr>> {
+        self.0
+            .send(Event::NewPeer(peer))
+            .map_err(|mpsc::SendError(event)| match event {
+                Event::NewPeer(peer) => mpsc::SendError(peer),
+                _ => unreachable!(),
+            })
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Config {
     pub exit_key: Key,
@@ -49,11 +70,17 @@ impl Events {
         config: Config,
     ) -> Events {
         let (tx, rx) = mpsc::channel();
+        let shutdown = Arc::new(AtomicBool::new(false));
+
         let _input_handle = {
             let tx = tx.clone();
+            let shutdown = shutdown.clone();
             thread::spawn(move || {
                 let stdin = io::stdin();
                 for evt in stdin.keys() {
+                    if shutdown.load(Ordering::Relaxed) {
+                        return;
+                    }
                     match evt {
                         Ok(key) => {
                             if let Err(_) = tx.send(Event::Input(key)) {
@@ -70,31 +97,58 @@ impl Events {
         };
         let _tick_handle = {
             let tx = tx.clone();
+            let shutdown = shutdown.clone();
             thread::spawn(move || loop {
-                tx.send(Event::Tick).unwrap();
+                if shutdown.load(Ordering::Relaxed) || tx.send(Event::Tick).is_err() {
+                    return;
+                }
                 thread::sleep(config.tick_rate);
             })
         };
         let _new_peer_handle = {
             let tx = tx.clone();
+            let shutdown = shutdown.clone();
             let peer_listener = DiscoveryService::new(Mode::Debug, public_key).unwrap();
             thread::spawn(move || loop {
-                if let Ok(ssb_peer) = peer_listener.recv() {
-                    let _res = tx.send(Event::NewPeer(ssb_peer));
+                if shutdown.load(Ordering::Relaxed) {
+                    return;
+                }
+                // A bounded recv, same as the peer-manager loop below, so
+                // this thread actually notices `shutdown` instead of
+                // blocking on the UDP socket indefinitely.
+                match peer_listener.recv_timeout(Duration::from_millis(250)) {
+                    Ok(ssb_peer) => {
+                        if tx.send(Event::NewPeer(ssb_peer)).is_err() {
+                            return;
+                        }
+                    }
+                    Err(_) => {}
                 }
             })
         };
         let _pm_handle = {
             let tx = tx.clone();
+            let shutdown = shutdown.clone();
             thread::spawn(move || loop {
-                if let Ok(pm_event) = peer_manager_rx.recv() {
-                    let _res = tx.send(Event::PeerManagerEvent(pm_event));
+                if shutdown.load(Ordering::Relaxed) {
+                    return;
+                }
+                match peer_manager_rx.recv_timeout(Duration::from_millis(250)) {
+                    Ok(pm_event) => {
+                        if tx.send(Event::PeerManagerEvent(pm_event)).is_err() {
+                            return;
+                        }
+                    }
+                    Err(mpsc::RecvTimeoutError::Timeout) => {}
+                    Err(mpsc::RecvTimeoutError::Disconnected) => return,
                 }
             })
         };
 
         Events {
             rx,
+            tx,
+            shutdown,
             _input_handle,
             _tick_handle,
             _new_peer_handle,
@@ -105,4 +159,28 @@ impl Events {
     pub fn next(&self) -> Result<Event<Key>, mpsc::RecvError> {
         self.rx.recv()
     }
+
+    /// A cloneable handle that lets other subsystems (gossip discovery)
+    /// push a `PeerAddr` into this same event queue as `Event::NewPeer`.
+    pub fn new_peer_sender(&self) -> NewPeerSender {
+        NewPeerSender(self.tx.clone())
+    }
+
+    /// Signals every background thread to stop and joins the ones that
+    /// can actually honor the signal promptly, so exiting doesn't leave
+    /// sockets running past process exit. The tick, discovery and
+    /// peer-manager loops all now poll `shutdown` on a bounded `recv`, so
+    /// each notices within one tick / 250ms.
+    ///
+    /// `_input_handle` is deliberately NOT joined. It returns on its own
+    /// right after forwarding the exit key; in any other case it is blocked
+    /// in `stdin().keys()`, which only returns on the next keypress, so
+    /// joining it here would hang `close()` until the user pressed another
+    /// key. It's left detached to die with the process instead.
+    pub fn close(self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        let _ = self._tick_handle.join();
+        let _ = self._pm_handle.join();
+        let _ = self._new_peer_handle.join();
+    }
 }
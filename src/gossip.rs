@@ -0,0 +1,279 @@
+use crate::discovery::{PeerAddr, Protocol};
+use ssb_crypto::PublicKey;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Cap on how many addresses we hand back in a single `Peers` reply, to
+/// keep a gossip round from turning into a connection storm.
+pub const MAX_PEERS_PER_REPLY: usize = 32;
+
+/// How often a connection proactively asks its peer for more addresses.
+pub const GET_PEERS_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Floor between outgoing `GetPeers` requests on a single connection.
+pub const GET_PEERS_RATE_LIMIT: Duration = Duration::from_secs(5);
+
+/// One peer address as carried in a `Peers` reply, tagged with whether its
+/// owner opted in to being gossiped about. Anything not marked `public`
+/// must never be re-advertised to a third party.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GossipedPeer {
+    pub peer: PeerAddr,
+    pub public: bool,
+}
+
+/// Tracks who told us about each public key and which keys have opted in
+/// to being gossiped, so a growing mesh doesn't turn into a connection
+/// storm or leak peers who asked to stay private. One `GossipState` is
+/// shared across every connection.
+pub struct GossipState {
+    /// Public key -> the public key of whoever first told us about it.
+    /// Used to satisfy "never re-advertise a peer back to the peer that
+    /// told you about it" without conflating "seen before" with "seen
+    /// from this particular source".
+    heard_from: HashMap<PublicKey, PublicKey>,
+    /// Keys that opted in to being gossiped, as learned from the `public`
+    /// flag on gossiped entries; anything not in here is never advertised.
+    public_peers: HashSet<PublicKey>,
+    /// Peers this node currently holds a live connection to, newest last.
+    /// A `Peers` reply is drawn from here, so a node can only ever
+    /// advertise addresses it has actually dialed or been dialed by.
+    connected: Vec<PeerAddr>,
+    /// Whether *this* node allows itself to be gossiped about. Nothing in
+    /// this module reads it and the handshake does not send it to peers,
+    /// so it does not change what other nodes advertise about this node.
+    pub public: bool,
+}
+
+impl GossipState {
+    pub fn new(public: bool) -> GossipState {
+        GossipState {
+            heard_from: HashMap::new(),
+            public_peers: HashSet::new(),
+            connected: Vec::new(),
+            public,
+        }
+    }
+
+    /// Marks `public_key` as having opted in to gossip. `observe` calls
+    /// this for every gossiped entry flagged `public`; the handshake does
+    /// not carry the flag, so a directly connected peer is marked only once
+    /// some node has gossiped it as public.
+    pub fn mark_public(&mut self, public_key: PublicKey) {
+        self.public_peers.insert(public_key);
+    }
+
+    pub fn is_public(&self, public_key: &PublicKey) -> bool {
+        self.public_peers.contains(public_key)
+    }
+
+    pub fn is_connected(&self, public_key: &PublicKey) -> bool {
+        self.connected.iter().any(|peer| &peer.public_key == public_key)
+    }
+
+    /// Records that `peer`'s connection just completed its handshake, so
+    /// it becomes eligible to be handed out in a `Peers` reply.
+    pub fn note_connected(&mut self, peer: PeerAddr) {
+        self.connected.push(peer);
+    }
+
+    /// Drops `public_key` from the connected set once its connection ends.
+    pub fn note_disconnected(&mut self, public_key: &PublicKey) {
+        self.connected.retain(|peer| &peer.public_key != public_key);
+    }
+
+    /// Builds a `Peers` reply for `requester`: newest-first, excluding the
+    /// requester itself, anything reached over a non-routable protocol,
+    /// anyone who hasn't opted in to being gossiped, and anyone we heard
+    /// about *from* the requester in the first place.
+    pub fn peers_to_advertise(&self, requester: &PublicKey) -> Vec<GossipedPeer> {
+        self.connected
+            .iter()
+            .rev()
+            .filter(|peer| &peer.public_key != requester)
+            .filter(|peer| peer.protocol == Protocol::Net)
+            .filter(|peer| self.is_public(&peer.public_key))
+            .filter(|peer| self.heard_from.get(&peer.public_key) != Some(requester))
+            .take(MAX_PEERS_PER_REPLY)
+            .cloned()
+            .map(|peer| GossipedPeer { peer, public: true })
+            .collect()
+    }
+
+    /// Records that `source` told us about `gossiped`, returning `true` if
+    /// this is the first time we've heard of that key at all (and so it's
+    /// worth dialing). The *first* source we hear a key from is the one
+    /// it's never re-advertised back to; later sources don't overwrite
+    /// that.
+    pub fn observe(&mut self, gossiped: &GossipedPeer, source: PublicKey) -> bool {
+        if gossiped.public {
+            self.mark_public(gossiped.peer.public_key);
+        }
+
+        match self.heard_from.entry(gossiped.peer.public_key) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(slot) => {
+                slot.insert(source);
+                true
+            }
+        }
+    }
+}
+
+/// `GossipState` shared across every connection, so a `GetPeers` reply can
+/// draw on peers learned or connected-to by any of them.
+pub type SharedGossipState = Arc<Mutex<GossipState>>;
+
+pub fn shared(state: GossipState) -> SharedGossipState {
+    Arc::new(Mutex::new(state))
+}
+
+/// Keeps a peer registered in a `SharedGossipState`'s connected set for as
+/// long as this guard lives, and removes it on drop — including on a panic
+/// unwind — so a connection can never linger in `peers_to_advertise` after
+/// it's actually gone.
+pub struct ConnectedGuard {
+    gossip: SharedGossipState,
+    public_key: PublicKey,
+}
+
+impl Drop for ConnectedGuard {
+    fn drop(&mut self) {
+        self.gossip.lock().unwrap().note_disconnected(&self.public_key);
+    }
+}
+
+/// Registers `peer` as connected in `gossip` and returns a guard that
+/// un-registers it again once dropped.
+pub fn track_connection(gossip: SharedGossipState, peer: PeerAddr) -> ConnectedGuard {
+    let public_key = peer.public_key;
+    gossip.lock().unwrap().note_connected(peer);
+    ConnectedGuard { gossip, public_key }
+}
+
+/// Per-connection throttle so we don't send `GetPeers` more than once every
+/// [`GET_PEERS_RATE_LIMIT`].
+pub struct GetPeersThrottle {
+    last_sent: Option<Instant>,
+}
+
+impl GetPeersThrottle {
+    pub fn new() -> GetPeersThrottle {
+        GetPeersThrottle { last_sent: None }
+    }
+
+    /// Returns whether a `GetPeers` may be sent right now, and if so,
+    /// records `now` as the last send so the next call has to wait out
+    /// the rate limit.
+    pub fn try_take(&mut self, now: Instant) -> bool {
+        let ready = match self.last_sent {
+            Some(last) => now.duration_since(last) >= GET_PEERS_RATE_LIMIT,
+            None => true,
+        };
+        if ready {
+            self.last_sent = Some(now);
+        }
+        ready
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+    fn pk(byte: u8) -> PublicKey {
+        PublicKey::from_slice(&[byte; 32]).unwrap()
+    }
+
+    fn peer(byte: u8, protocol: Protocol) -> PeerAddr {
+        PeerAddr {
+            public_key: pk(byte),
+            socket_addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, byte)), 8008),
+            protocol,
+        }
+    }
+
+    #[test]
+    fn excludes_the_requester_itself() {
+        let mut state = GossipState::new(true);
+        let requester = pk(1);
+        state.mark_public(requester);
+        state.note_connected(peer(1, Protocol::Net));
+        assert!(state.peers_to_advertise(&requester).is_empty());
+    }
+
+    #[test]
+    fn excludes_peers_that_have_not_opted_into_gossip() {
+        let mut state = GossipState::new(true);
+        state.note_connected(peer(2, Protocol::Net));
+        assert!(state.peers_to_advertise(&pk(1)).is_empty());
+    }
+
+    #[test]
+    fn excludes_non_net_protocol_peers() {
+        let mut state = GossipState::new(true);
+        state.mark_public(pk(2));
+        state.note_connected(peer(2, Protocol::Local));
+        assert!(state.peers_to_advertise(&pk(1)).is_empty());
+    }
+
+    #[test]
+    fn excludes_peers_heard_about_from_the_requester() {
+        let mut state = GossipState::new(true);
+        let requester = pk(1);
+        let gossiped = GossipedPeer {
+            peer: peer(2, Protocol::Net),
+            public: true,
+        };
+        state.observe(&gossiped, requester);
+        state.note_connected(peer(2, Protocol::Net));
+        assert!(state.peers_to_advertise(&requester).is_empty());
+    }
+
+    #[test]
+    fn advertises_eligible_peers_newest_first() {
+        let mut state = GossipState::new(true);
+        state.mark_public(pk(2));
+        state.mark_public(pk(3));
+        state.note_connected(peer(2, Protocol::Net));
+        state.note_connected(peer(3, Protocol::Net));
+        let advertised = state.peers_to_advertise(&pk(1));
+        assert_eq!(advertised.len(), 2);
+        assert_eq!(advertised[0].peer.public_key, pk(3));
+        assert_eq!(advertised[1].peer.public_key, pk(2));
+    }
+
+    #[test]
+    fn is_connected_reflects_note_connected_and_note_disconnected() {
+        let mut state = GossipState::new(true);
+        let key = pk(1);
+        assert!(!state.is_connected(&key));
+        state.note_connected(peer(1, Protocol::Net));
+        assert!(state.is_connected(&key));
+        state.note_disconnected(&key);
+        assert!(!state.is_connected(&key));
+    }
+
+    #[test]
+    fn observe_returns_true_only_for_the_first_source() {
+        let mut state = GossipState::new(true);
+        let gossiped = GossipedPeer {
+            peer: peer(2, Protocol::Net),
+            public: false,
+        };
+        assert!(state.observe(&gossiped, pk(1)));
+        assert!(!state.observe(&gossiped, pk(3)));
+    }
+
+    #[test]
+    fn get_peers_throttle_enforces_the_rate_limit() {
+        let mut throttle = GetPeersThrottle::new();
+        let now = Instant::now();
+        assert!(throttle.try_take(now));
+        assert!(!throttle.try_take(now));
+        assert!(throttle.try_take(now + GET_PEERS_RATE_LIMIT));
+    }
+}
@@ -0,0 +1,313 @@
+use crate::discovery::{PeerAddr, Protocol};
+use crate::gossip::GossipedPeer;
+use ssb_crypto::PublicKey;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// Wire messages exchanged over a peer's box stream once the handshake has
+/// completed. Every frame is `[1-byte tag][payload]`; chat text is just one
+/// variant among these rather than the whole wire format, which leaves room
+/// for control traffic (presence, keepalives, gossip) to ride the same
+/// connection.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    Chat(String),
+    Ping,
+    Pong,
+    GetPeers,
+    Peers(Vec<GossipedPeer>),
+    Goodbye,
+}
+
+const TAG_CHAT: u8 = 0;
+const TAG_PING: u8 = 1;
+const TAG_PONG: u8 = 2;
+const TAG_GET_PEERS: u8 = 3;
+const TAG_PEERS: u8 = 4;
+const TAG_GOODBYE: u8 = 5;
+
+#[derive(Snafu, Debug)]
+pub enum MessageError {
+    #[snafu(display("Chat payload was not valid UTF-8"))]
+    MalformedChat,
+    #[snafu(display("Peers payload was truncated or corrupt"))]
+    MalformedPeers,
+}
+
+impl Message {
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            Message::Chat(text) => {
+                let mut buf = Vec::with_capacity(1 + text.len());
+                buf.push(TAG_CHAT);
+                buf.extend_from_slice(text.as_bytes());
+                buf
+            }
+            Message::Ping => vec![TAG_PING],
+            Message::Pong => vec![TAG_PONG],
+            Message::GetPeers => vec![TAG_GET_PEERS],
+            Message::Peers(peers) => {
+                let mut buf = vec![TAG_PEERS];
+                encode_peers(peers, &mut buf);
+                buf
+            }
+            Message::Goodbye => vec![TAG_GOODBYE],
+        }
+    }
+
+    /// Decodes a single frame. An unrecognized tag yields `Ok(None)` so
+    /// callers can skip it for forward-compat; `Err` is reserved for a
+    /// *known* tag whose payload doesn't parse.
+    pub fn decode(frame: &[u8]) -> Result<Option<Message>, MessageError> {
+        let (tag, payload) = match frame.split_first() {
+            Some((tag, payload)) => (*tag, payload),
+            None => return Ok(None),
+        };
+
+        let message = match tag {
+            TAG_CHAT => Message::Chat(
+                String::from_utf8(payload.to_vec()).map_err(|_| MessageError::MalformedChat)?,
+            ),
+            TAG_PING => Message::Ping,
+            TAG_PONG => Message::Pong,
+            TAG_GET_PEERS => Message::GetPeers,
+            TAG_PEERS => Message::Peers(decode_peers(payload)?),
+            TAG_GOODBYE => Message::Goodbye,
+            _ => return Ok(None),
+        };
+
+        Ok(Some(message))
+    }
+}
+
+fn encode_peers(peers: &[GossipedPeer], buf: &mut Vec<u8>) {
+    buf.extend_from_slice(&(peers.len() as u32).to_be_bytes());
+    for gossiped in peers {
+        buf.extend_from_slice(gossiped.peer.public_key.as_ref());
+        encode_socket_addr(&gossiped.peer.socket_addr, buf);
+        buf.push(encode_protocol(&gossiped.peer.protocol));
+        buf.push(gossiped.public as u8);
+    }
+}
+
+/// Smallest an encoded entry could possibly be: a 32-byte public key, a
+/// 1-byte address-kind tag, a 4-byte IPv4 address, a 2-byte port, a
+/// 1-byte protocol tag, and the 1-byte public/opt-out flag.
+const MIN_PEER_ENTRY_SIZE: usize = 32 + 1 + 4 + 2 + 1 + 1;
+
+fn decode_peers(payload: &[u8]) -> Result<Vec<GossipedPeer>, MessageError> {
+    let mut cursor = payload;
+    let count = take_u32(&mut cursor).ok_or(MessageError::MalformedPeers)? as usize;
+
+    // `count` is attacker-controlled; make sure the payload could actually
+    // hold that many entries before trusting it as an allocation size.
+    if count > cursor.len() / MIN_PEER_ENTRY_SIZE {
+        return Err(MessageError::MalformedPeers);
+    }
+
+    let mut peers = Vec::with_capacity(count);
+
+    for _ in 0..count {
+        let public_key = take_public_key(&mut cursor).ok_or(MessageError::MalformedPeers)?;
+        let socket_addr = take_socket_addr(&mut cursor).ok_or(MessageError::MalformedPeers)?;
+        let protocol = take_protocol(&mut cursor).ok_or(MessageError::MalformedPeers)?;
+        let public = take_u8(&mut cursor).ok_or(MessageError::MalformedPeers)? != 0;
+        peers.push(GossipedPeer {
+            peer: PeerAddr {
+                public_key,
+                socket_addr,
+                protocol,
+            },
+            public,
+        });
+    }
+
+    Ok(peers)
+}
+
+fn take_u8(cursor: &mut &[u8]) -> Option<u8> {
+    if cursor.is_empty() {
+        return None;
+    }
+    let (head, tail) = cursor.split_at(1);
+    *cursor = tail;
+    Some(head[0])
+}
+
+fn take_u32(cursor: &mut &[u8]) -> Option<u32> {
+    if cursor.len() < 4 {
+        return None;
+    }
+    let (head, tail) = cursor.split_at(4);
+    *cursor = tail;
+    Some(u32::from_be_bytes([head[0], head[1], head[2], head[3]]))
+}
+
+fn take_public_key(cursor: &mut &[u8]) -> Option<PublicKey> {
+    if cursor.len() < 32 {
+        return None;
+    }
+    let (head, tail) = cursor.split_at(32);
+    *cursor = tail;
+    PublicKey::from_slice(head)
+}
+
+fn encode_socket_addr(addr: &SocketAddr, buf: &mut Vec<u8>) {
+    match addr {
+        SocketAddr::V4(v4) => {
+            buf.push(4);
+            buf.extend_from_slice(&v4.ip().octets());
+            buf.extend_from_slice(&v4.port().to_be_bytes());
+        }
+        SocketAddr::V6(v6) => {
+            buf.push(6);
+            buf.extend_from_slice(&v6.ip().octets());
+            buf.extend_from_slice(&v6.port().to_be_bytes());
+        }
+    }
+}
+
+fn take_socket_addr(cursor: &mut &[u8]) -> Option<SocketAddr> {
+    match take_u8(cursor)? {
+        4 => {
+            if cursor.len() < 6 {
+                return None;
+            }
+            let (ip, tail) = cursor.split_at(4);
+            let (port, tail) = tail.split_at(2);
+            *cursor = tail;
+            let ip = Ipv4Addr::new(ip[0], ip[1], ip[2], ip[3]);
+            Some(SocketAddr::new(
+                IpAddr::V4(ip),
+                u16::from_be_bytes([port[0], port[1]]),
+            ))
+        }
+        6 => {
+            if cursor.len() < 18 {
+                return None;
+            }
+            let (ip, tail) = cursor.split_at(16);
+            let (port, tail) = tail.split_at(2);
+            *cursor = tail;
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(ip);
+            Some(SocketAddr::new(
+                IpAddr::V6(Ipv6Addr::from(octets)),
+                u16::from_be_bytes([port[0], port[1]]),
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn encode_protocol(protocol: &Protocol) -> u8 {
+    match protocol {
+        Protocol::Net => 0,
+        Protocol::Local => 1,
+    }
+}
+
+fn take_protocol(cursor: &mut &[u8]) -> Option<Protocol> {
+    match take_u8(cursor)? {
+        0 => Some(Protocol::Net),
+        1 => Some(Protocol::Local),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pk(byte: u8) -> PublicKey {
+        PublicKey::from_slice(&[byte; 32]).unwrap()
+    }
+
+    fn peer(byte: u8) -> PeerAddr {
+        PeerAddr {
+            public_key: pk(byte),
+            socket_addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, byte)), 8008),
+            protocol: Protocol::Net,
+        }
+    }
+
+    #[test]
+    fn chat_round_trips() {
+        let message = Message::Chat("hello".to_string());
+        assert_eq!(Message::decode(&message.encode()).unwrap(), Some(message));
+    }
+
+    #[test]
+    fn control_messages_round_trip() {
+        let messages = vec![Message::Ping, Message::Pong, Message::GetPeers, Message::Goodbye];
+        for message in messages {
+            assert_eq!(Message::decode(&message.encode()).unwrap(), Some(message));
+        }
+    }
+
+    #[test]
+    fn peers_round_trips_with_v4_and_v6() {
+        let mut v6 = peer(1);
+        v6.socket_addr = SocketAddr::new(IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1)), 8008);
+        let message = Message::Peers(vec![
+            GossipedPeer {
+                peer: peer(1),
+                public: true,
+            },
+            GossipedPeer {
+                peer: v6,
+                public: false,
+            },
+        ]);
+        assert_eq!(Message::decode(&message.encode()).unwrap(), Some(message));
+    }
+
+    #[test]
+    fn empty_frame_decodes_to_none() {
+        assert_eq!(Message::decode(&[]).unwrap(), None);
+    }
+
+    #[test]
+    fn unrecognized_tag_is_skipped_not_errored() {
+        assert_eq!(Message::decode(&[0xFF]).unwrap(), None);
+    }
+
+    #[test]
+    fn chat_rejects_invalid_utf8() {
+        let frame = vec![TAG_CHAT, 0xFF, 0xFE];
+        assert!(matches!(
+            Message::decode(&frame),
+            Err(MessageError::MalformedChat)
+        ));
+    }
+
+    #[test]
+    fn peers_rejects_truncated_count() {
+        let frame = vec![TAG_PEERS, 0, 0];
+        assert!(matches!(
+            Message::decode(&frame),
+            Err(MessageError::MalformedPeers)
+        ));
+    }
+
+    #[test]
+    fn peers_rejects_count_that_overstates_the_payload() {
+        let mut frame = vec![TAG_PEERS];
+        frame.extend_from_slice(&1_000_000u32.to_be_bytes());
+        assert!(matches!(
+            Message::decode(&frame),
+            Err(MessageError::MalformedPeers)
+        ));
+    }
+
+    #[test]
+    fn peers_rejects_an_entry_truncated_mid_address() {
+        let mut frame = vec![TAG_PEERS];
+        frame.extend_from_slice(&1u32.to_be_bytes());
+        frame.extend_from_slice(pk(1).as_ref());
+        frame.push(4); // v4 tag, but no address/port bytes follow
+        assert!(matches!(
+            Message::decode(&frame),
+            Err(MessageError::MalformedPeers)
+        ));
+    }
+}
@@ -0,0 +1,201 @@
+use crate::discovery::PeerAddr;
+use crate::peer_connection::Handshaker;
+use crate::peer_manager::{PeerEvent, PeerManagerEvent};
+use ssb_crypto::PublicKey;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const BASE_DELAY: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(60);
+const STABLE_AFTER: Duration = Duration::from_secs(30);
+
+/// Capped exponential backoff: `delay = min(base * 2^attempt, max_cap)`.
+/// Callers should sleep a value sampled uniformly from `[0, delay]` (full
+/// jitter, see [`jittered`]) rather than `delay` itself.
+struct Backoff {
+    base: Duration,
+    max_cap: Duration,
+    attempt: u32,
+}
+
+impl Backoff {
+    fn new(base: Duration, max_cap: Duration) -> Backoff {
+        Backoff {
+            base,
+            max_cap,
+            attempt: 0,
+        }
+    }
+
+    fn next_delay(&mut self) -> Duration {
+        let exp = 2u32.saturating_pow(self.attempt);
+        let delay = self.base.checked_mul(exp).unwrap_or(self.max_cap);
+        self.attempt = self.attempt.saturating_add(1);
+        delay.min(self.max_cap)
+    }
+
+    fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+/// Samples uniformly from `[0, delay]` (full jitter) so many supervisors
+/// backing off at once don't all redial in lockstep.
+fn jittered(delay: Duration) -> Duration {
+    let delay_ms = delay.as_millis() as u64;
+    if delay_ms == 0 {
+        return delay;
+    }
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as u64)
+        .unwrap_or(0);
+    Duration::from_millis(seed % (delay_ms + 1))
+}
+
+/// Public keys with a `ReconnectSupervisor` currently dialing them, shared
+/// across every call site that might spawn one, so two callers racing to
+/// reconnect the same peer (e.g. a dropped-connection event and a manual
+/// retry) can't end up with two supervisors redialing it in parallel.
+pub type SharedReconnectRegistry = Arc<Mutex<HashSet<PublicKey>>>;
+
+pub fn shared_registry() -> SharedReconnectRegistry {
+    Arc::new(Mutex::new(HashSet::new()))
+}
+
+/// Removes a public key from the registry on every exit path out of the
+/// supervisor's loop, including a panic unwind, so a crashed supervisor
+/// can never leave its peer permanently un-reconnectable.
+struct RegistryGuard {
+    registry: SharedReconnectRegistry,
+    public_key: PublicKey,
+}
+
+impl Drop for RegistryGuard {
+    fn drop(&mut self) {
+        self.registry.lock().unwrap().remove(&self.public_key);
+    }
+}
+
+/// Watches a single peer for disconnection and re-dials it with capped
+/// exponential backoff and full jitter, so a transient drop doesn't lose
+/// the peer for good. One supervisor runs per peer; cancel it when the
+/// user explicitly disconnects.
+pub struct ReconnectSupervisor {
+    cancelled: Arc<AtomicBool>,
+    _loop_handle: thread::JoinHandle<()>,
+}
+
+impl ReconnectSupervisor {
+    /// Spawns the supervisor, unless `registry` already has a supervisor
+    /// dialing `peer.public_key` — in which case this returns `None` and
+    /// leaves the existing supervisor in charge. `max_attempts` of `None`
+    /// means retry forever; otherwise a `PeerEvent::Unreachable` is
+    /// emitted once attempts are exhausted and the supervisor gives up.
+    pub fn spawn(
+        handshaker: Handshaker,
+        peer: PeerAddr,
+        event_bus: mpsc::Sender<PeerManagerEvent>,
+        max_attempts: Option<u32>,
+        registry: SharedReconnectRegistry,
+    ) -> Option<ReconnectSupervisor> {
+        if !registry.lock().unwrap().insert(peer.public_key) {
+            return None;
+        }
+
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let loop_cancelled = cancelled.clone();
+
+        let _loop_handle = thread::spawn(move || {
+            let _guard = RegistryGuard {
+                registry,
+                public_key: peer.public_key,
+            };
+            let mut backoff = Backoff::new(BASE_DELAY, MAX_DELAY);
+            let mut attempts = 0u32;
+
+            while !loop_cancelled.load(Ordering::Relaxed) {
+                if let Ok(connection) = handshaker.client_handshake(peer.clone()) {
+                    let connected_at = Instant::now();
+                    let _ = connection.wait_until_closed();
+
+                    if connected_at.elapsed() >= STABLE_AFTER {
+                        backoff.reset();
+                        attempts = 0;
+                    }
+                }
+
+                if loop_cancelled.load(Ordering::Relaxed) {
+                    return;
+                }
+
+                attempts += 1;
+                if let Some(max) = max_attempts {
+                    if attempts > max {
+                        let _ = event_bus.send(PeerManagerEvent {
+                            peer: peer.clone(),
+                            event: PeerEvent::Unreachable,
+                        });
+                        return;
+                    }
+                }
+
+                thread::sleep(jittered(backoff.next_delay()));
+            }
+        });
+
+        Some(ReconnectSupervisor {
+            cancelled,
+            _loop_handle,
+        })
+    }
+
+    /// Stops the supervisor from dialing again after its current sleep or
+    /// in-flight attempt.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_delay_doubles_each_attempt_until_capped() {
+        let mut backoff = Backoff::new(Duration::from_millis(100), Duration::from_secs(1));
+        assert_eq!(backoff.next_delay(), Duration::from_millis(100));
+        assert_eq!(backoff.next_delay(), Duration::from_millis(200));
+        assert_eq!(backoff.next_delay(), Duration::from_millis(400));
+        assert_eq!(backoff.next_delay(), Duration::from_millis(800));
+        // Would be 1600ms uncapped; clamped to the 1s cap instead.
+        assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+        assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn reset_restarts_the_sequence_from_the_base_delay() {
+        let mut backoff = Backoff::new(Duration::from_millis(100), Duration::from_secs(1));
+        backoff.next_delay();
+        backoff.next_delay();
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn jittered_never_exceeds_the_input_delay() {
+        let delay = Duration::from_millis(250);
+        for _ in 0..100 {
+            assert!(jittered(delay) <= delay);
+        }
+    }
+
+    #[test]
+    fn jittered_of_zero_is_zero() {
+        assert_eq!(jittered(Duration::from_millis(0)), Duration::from_millis(0));
+    }
+}
@@ -0,0 +1,71 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// How often the traffic reporter loop samples each connection's counters
+/// into a snapshot for the UI.
+pub const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Lock-free traffic counters for one side of a peer connection, shared
+/// between the thread that records activity (reader or writer loop) and
+/// whatever thread samples it for reporting. Counters only ever increase,
+/// so a `Relaxed` ordering is enough: readers just need the freshest value
+/// eventually, not a synchronization point with anything else.
+pub struct TrafficStats {
+    bytes: AtomicU64,
+    messages: AtomicU64,
+    /// Milliseconds since `started_at` as of the last `record()` call, so
+    /// `idle_for` can be computed from a single atomic read instead of an
+    /// `Instant` (which isn't `Copy`-into-an-atomic).
+    last_activity_millis: AtomicU64,
+    started_at: Instant,
+}
+
+impl TrafficStats {
+    pub fn new() -> TrafficStats {
+        TrafficStats {
+            bytes: AtomicU64::new(0),
+            messages: AtomicU64::new(0),
+            last_activity_millis: AtomicU64::new(0),
+            started_at: Instant::now(),
+        }
+    }
+
+    /// Called from the owning reader/writer loop every time a frame
+    /// crosses the wire.
+    pub fn record(&self, bytes: usize) {
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.messages.fetch_add(1, Ordering::Relaxed);
+        self.last_activity_millis.store(
+            self.started_at.elapsed().as_millis() as u64,
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Takes a point-in-time snapshot. Safe to call from any thread at any
+    /// cadence; unlike the old frame-gated design, this never depends on a
+    /// frame having just arrived, so a genuinely silent peer still gets an
+    /// up-to-date `idle_for`.
+    pub fn snapshot(&self) -> TrafficSnapshot {
+        let last_activity_millis = self.last_activity_millis.load(Ordering::Relaxed);
+        let idle_for = self
+            .started_at
+            .elapsed()
+            .checked_sub(Duration::from_millis(last_activity_millis))
+            .unwrap_or_default();
+
+        TrafficSnapshot {
+            bytes: self.bytes.load(Ordering::Relaxed),
+            messages: self.messages.load(Ordering::Relaxed),
+            idle_for,
+        }
+    }
+}
+
+/// Point-in-time view of a `TrafficStats`, carried on a `PeerEvent` so the
+/// UI can render throughput and flag peers silent beyond a timeout.
+#[derive(Debug, Clone, Copy)]
+pub struct TrafficSnapshot {
+    pub bytes: u64,
+    pub messages: u64,
+    pub idle_for: Duration,
+}